@@ -1,3 +1,6 @@
+use crate::label::{Fixup, FixupWidth, Label};
+use crate::object::{Relocation, RelocationKind};
+
 pub type RegisterId = u8;
 
 macro_rules! decide {
@@ -43,8 +46,17 @@ impl From<Registers> for RegisterId {
 pub enum Operand {
     /// A register operand.
     Register(RegisterId),
-    /// A memory operand with an offset.
-    MemoryAndOffset(RegisterId, u32),
+    /// A memory operand, made up of an optional base register, an optional
+    /// scaled index register, and a 32-bit displacement.
+    ///
+    /// `base: None` produces a RIP-relative operand. `index` is scaled by
+    /// `scale`, which must be one of 1, 2, 4 or 8.
+    Memory {
+        base: Option<RegisterId>,
+        index: Option<RegisterId>,
+        scale: u8,
+        disp: i32,
+    },
     /// A 64-bit immediate operand.
     Imm64(u64),
     /// A 32-bit immediate operand.
@@ -59,9 +71,31 @@ impl Operand {
         Operand::Register(reg.into())
     }
 
-    /// This function creates a new memory operand with an offset.
+    /// This function creates a new `[base + disp]` memory operand.
     pub fn memory_and_offset<S: Into<RegisterId>>(reg: S, offset: u32) -> Self {
-        Operand::MemoryAndOffset(reg.into(), offset)
+        Operand::Memory { base: Some(reg.into()), index: None, scale: 1, disp: offset as i32 }
+    }
+
+    /// This function creates a new `[base + index * scale + disp]` memory operand.
+    pub fn memory<S: Into<RegisterId>, I: Into<RegisterId>>(base: S, index: I, scale: u8, disp: i32) -> Self {
+        assert!(matches!(scale, 1 | 2 | 4 | 8), "scale must be one of 1, 2, 4, or 8");
+
+        let index = index.into();
+
+        // RSP (index field 0b100 in the SIB byte with REX.X clear) is reserved
+        // to mean "no index", so it cannot be encoded as an index. R12 shares
+        // those low three bits and is rejected as well.
+        assert!(
+            index & 0x7 != 0b100,
+            "RSP/R12 cannot be used as an index register"
+        );
+
+        Operand::Memory { base: Some(base.into()), index: Some(index), scale, disp }
+    }
+
+    /// This function creates a new RIP-relative memory operand.
+    pub fn rip_relative(disp: i32) -> Self {
+        Operand::Memory { base: None, index: None, scale: 1, disp }
     }
 
     /// This function creates a new 64-bit immediate operand.
@@ -75,29 +109,239 @@ impl Operand {
     }
 }
 
+/// This enum represents the operand size of an instruction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Width {
+    /// An 8-bit operand.
+    Byte,
+    /// A 16-bit operand, selected with the `0x66` operand-size prefix.
+    Word,
+    /// A 32-bit operand, the default operand size in 64-bit mode.
+    Dword,
+    /// A 64-bit operand, selected with REX.W.
+    Qword,
+}
+
+/// This function returns whether `reg` refers to one of RSP/RBP/RSI/RDI, whose
+/// low byte is SPL/BPL/SIL/DIL when accessed with a REX prefix, and AH/CH/DH/BH
+/// without one.
+fn is_uniform_byte_register(reg: RegisterId) -> bool {
+    matches!(reg, 4..=7)
+}
+
+/// This enum represents the x86 condition codes used by conditional jumps.
+/// Its discriminant is the `tttn` nibble that is OR'd into the `jcc` opcode.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Condition {
+    Overflow = 0x0,
+    NotOverflow = 0x1,
+    Below = 0x2,
+    AboveOrEqual = 0x3,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    BelowOrEqual = 0x6,
+    Above = 0x7,
+    Sign = 0x8,
+    NotSign = 0x9,
+    Parity = 0xA,
+    NotParity = 0xB,
+    Less = 0xC,
+    GreaterOrEqual = 0xD,
+    LessOrEqual = 0xE,
+    Greater = 0xF,
+}
+
 /// The assembler is a helper class to generate x86_64 machine code.
+#[derive(Default)]
 pub struct Assembler {
     /// The generated code.
     pub code: Vec<u8>,
+    /// The relocations recorded by [`Assembler::call_extern`], to be carried
+    /// into an [`ObjectWriter`](crate::object::ObjectWriter)'s `.rela.text`.
+    pub relocations: Vec<Relocation>,
+    /// The number of labels that have a pending fixup but have not been
+    /// bound yet. Checked by [`Assembler::finalize`].
+    unbound_labels: usize,
 }
 
 impl Assembler {
     /// This function creates a new assembler.
     pub fn new() -> Self {
-        Self { code: Vec::new() }
+        Self::default()
     }
 
     /// This function consumes self and returns the generated code.
+    ///
+    /// Panics if any label was referenced by a jump or call but never
+    /// bound with [`Assembler::bind`].
     pub fn finalize(self) -> Box<[u8]> {
+        assert_eq!(self.unbound_labels, 0, "found a label that was referenced but never bound");
+
         self.code.into_boxed_slice()
     }
 
+    /// This function binds `label` to the current end of the code buffer,
+    /// patching every pending fixup that was recorded while the label was
+    /// unbound.
+    ///
+    /// Params:
+    ///  - `label`: the label to bind
+    pub fn bind(&mut self, label: &mut Label) {
+        let target = self.code.len();
+
+        if !label.fixups.is_empty() {
+            self.unbound_labels -= 1;
+        }
+
+        label.position = Some(target);
+
+        for fixup in label.fixups.drain(..) {
+            match fixup.width {
+                FixupWidth::Rel8 => {
+                    let disp = target as isize - (fixup.offset + 1) as isize;
+
+                    assert!(
+                        disp >= i8::MIN as isize && disp <= i8::MAX as isize,
+                        "rel8 displacement out of range"
+                    );
+
+                    self.code[fixup.offset] = disp as i8 as u8;
+                }
+
+                FixupWidth::Rel32 => {
+                    let disp = target as isize - (fixup.offset + 4) as isize;
+
+                    self.patch32(disp as u32, fixup.offset);
+                }
+            }
+        }
+    }
+
+    /// This function emits either a direct displacement (if `label` is
+    /// already bound) or a zero placeholder plus a fixup site (if it is
+    /// not), according to `width`.
+    ///
+    /// Params:
+    ///  - `label`: the label to resolve
+    ///  - `width`: whether to emit a rel8 or rel32 displacement
+    fn resolve_label(&mut self, label: &mut Label, width: FixupWidth) {
+        match label.position {
+            Some(target) => match width {
+                FixupWidth::Rel8 => {
+                    let pos = self.code.len();
+                    let disp = target as isize - (pos + 1) as isize;
+
+                    assert!(
+                        disp >= i8::MIN as isize && disp <= i8::MAX as isize,
+                        "rel8 displacement out of range"
+                    );
+
+                    self.emit8(disp as i8 as u8);
+                }
+
+                FixupWidth::Rel32 => {
+                    let pos = self.code.len();
+                    let disp = target as isize - (pos + 4) as isize;
+
+                    self.emit32(disp as u32);
+                }
+            },
+
+            None => {
+                if label.fixups.is_empty() {
+                    self.unbound_labels += 1;
+                }
+
+                let offset = self.code.len();
+
+                label.fixups.push(Fixup { offset, width });
+
+                match width {
+                    FixupWidth::Rel8 => self.emit8(0),
+                    FixupWidth::Rel32 => self.emit32(0),
+                }
+            }
+        }
+    }
+
+    /// This function generates a near (rel32) unconditional jump to `label`,
+    /// resolving it immediately if already bound or registering a fixup
+    /// otherwise.
+    ///
+    /// Params:
+    ///  - `label`: the label to jump to
+    pub fn jmp(&mut self, label: &mut Label) -> usize {
+        self.emit8(0xE9);
+
+        let pos = self.code.len();
+
+        self.resolve_label(label, FixupWidth::Rel32);
+
+        pos
+    }
+
+    /// This function generates a short (rel8) unconditional jump to `label`,
+    /// resolving it immediately if already bound or registering a fixup
+    /// otherwise.
+    ///
+    /// Panics (via [`Assembler::bind`] or [`Assembler::resolve_label`]) if the
+    /// displacement to `label` does not fit in a signed byte.
+    ///
+    /// Params:
+    ///  - `label`: the label to jump to
+    pub fn jmp_short(&mut self, label: &mut Label) -> usize {
+        self.emit8(0xEB);
+
+        let pos = self.code.len();
+
+        self.resolve_label(label, FixupWidth::Rel8);
+
+        pos
+    }
+
+    /// This function generates a near (rel32) call to `label`, resolving it
+    /// immediately if already bound or registering a fixup otherwise.
+    ///
+    /// Params:
+    ///  - `label`: the label to call
+    pub fn call(&mut self, label: &mut Label) -> usize {
+        self.emit8(0xE8);
+
+        let pos = self.code.len();
+
+        self.resolve_label(label, FixupWidth::Rel32);
+
+        pos
+    }
+
+    /// This function generates a near call to an as-yet-unknown external symbol,
+    /// e.g. another function or a libc routine. Rather than resolving a
+    /// displacement, it records an `R_X86_64_PLT32` relocation against `symbol`
+    /// for an [`ObjectWriter`](crate::object::ObjectWriter) to carry in
+    /// `.rela.text`, leaving the actual address for the linker to fill in.
+    ///
+    /// Params:
+    ///  - `symbol`: the name of the external symbol to call
+    pub fn call_extern<S: Into<String>>(&mut self, symbol: S) -> usize {
+        self.emit8(0xE8);
+
+        let pos = self.code.len();
+
+        self.relocations.push(Relocation { offset: pos, symbol: symbol.into(), kind: RelocationKind::Plt32 });
+
+        self.emit32(0);
+
+        pos
+    }
+
     /// This function generates a move instruction from the source to the destination.
     ///
     /// Params:
+    ///  - `width`: the operand size
     ///  - `dst`: the destination operand
     ///  - `src`: the source operand
-    pub fn mov<A: Into<Operand>, B: Into<Operand>>(&mut self, dst: A, src: B) {
+    pub fn mov<A: Into<Operand>, B: Into<Operand>>(&mut self, width: Width, dst: A, src: B) {
         let dst = dst.into();
         let src = src.into();
 
@@ -109,31 +353,29 @@ impl Assembler {
             Operand::Register(dst_reg) => match src {
                 // mov dst, src
                 Operand::Register(src_reg) => {
-                    self.emit_rex_prefix(dst_reg, src_reg);
+                    self.emit_rex_prefix(width, dst_reg, src_reg);
 
-                    self.emit8(0x89);
+                    self.emit8(if width == Width::Byte { 0x88 } else { 0x89 });
 
                     self.emit8(0xC0
                         | ((src_reg & 0x7) << 3)
                         | (dst_reg & 0x7));
                 }
 
-                // mov dst, [src+offset]
-                Operand::MemoryAndOffset(src_reg, src_offset) => {
-                    self.emit_rex_prefix(src_reg, dst_reg);
-
-                    self.emit8(0x8B);
+                // mov dst, [mem]
+                Operand::Memory { base, index, scale, disp } => {
+                    self.emit_rex_prefix_mem(width, dst_reg, base, index);
 
-                    self.emit8(0x80
-                        | ((dst_reg & 0x7) << 3)
-                        | (src_reg & 0x7));
+                    self.emit8(if width == Width::Byte { 0x8A } else { 0x8B });
 
-                    self.emit32(src_offset);
+                    self.emit_memory_operand(dst_reg, base, index, scale, disp);
                 }
 
                 // mov dst, imm64
                 Operand::Imm64(imm64) => {
-                    self.emit_rex_prefix(dst_reg, 0);
+                    assert_eq!(width, Width::Qword, "a 64-bit immediate requires Qword width");
+
+                    self.emit_rex_prefix(width, dst_reg, 0);
 
                     self.emit8(0xB8 | ((dst_reg & 0x7) << 3));
 
@@ -142,22 +384,18 @@ impl Assembler {
 
                 op => panic!("Invalid source: {:?}", op)
             },
-            Operand::MemoryAndOffset(dst_reg, dst_offset) => match src {
-                // mov [dst+offset], src
+            Operand::Memory { base: dst_base, index: dst_index, scale: dst_scale, disp: dst_disp } => match src {
+                // mov [mem], src
                 Operand::Register(src_reg) => {
-                    self.emit_rex_prefix(dst_reg, src_reg);
-
-                    self.emit8(0x89);
+                    self.emit_rex_prefix_mem(width, src_reg, dst_base, dst_index);
 
-                    self.emit8(0x80
-                        | (src_reg & 7) << 3
-                        | (dst_reg & 7));
+                    self.emit8(if width == Width::Byte { 0x88 } else { 0x89 });
 
-                    self.emit32(dst_offset);
+                    self.emit_memory_operand(src_reg, dst_base, dst_index, dst_scale, dst_disp);
                 }
 
                 Operand::Imm64(..) | Operand::Imm32(..) => panic!("impossible to move an immediate to memory"),
-                Operand::MemoryAndOffset(..) => panic!("impossible to move from memory to memory"),
+                Operand::Memory { .. } => panic!("impossible to move from memory to memory"),
 
                 op => panic!("Invalid source: {:?}", op)
             },
@@ -169,9 +407,10 @@ impl Assembler {
     /// This function generates an add instruction that adds the source to the destination.
     ///
     /// Params:
+    ///  - `width`: the operand size
     ///  - `dst`: the destination operand
     ///  - `src`: the source operand
-    pub fn add<A: Into<Operand>, B: Into<Operand>>(&mut self, dst: A, src: B) {
+    pub fn add<A: Into<Operand>, B: Into<Operand>>(&mut self, width: Width, dst: A, src: B) {
         let dst = dst.into();
         let src = src.into();
 
@@ -179,31 +418,40 @@ impl Assembler {
             Operand::Register(dst_reg) => match src {
                 // add dst, src
                 Operand::Register(src_reg) => {
-                    self.emit_rex_prefix(dst_reg, src_reg);
+                    self.emit_rex_prefix(width, dst_reg, src_reg);
 
-                    self.emit8(0x01);
+                    self.emit8(if width == Width::Byte { 0x00 } else { 0x01 });
 
                     self.emit8(0xC0
                         | ((src_reg & 0x7) << 3)
                         | (dst_reg & 0x7));
                 }
 
-                // add dst, [src+offset]
-                Operand::MemoryAndOffset(src_reg, src_offset) => {
-                    self.emit_rex_prefix(src_reg, dst_reg);
+                // add dst, [mem]
+                Operand::Memory { base, index, scale, disp } => {
+                    self.emit_rex_prefix_mem(width, dst_reg, base, index);
+
+                    self.emit8(if width == Width::Byte { 0x02 } else { 0x03 });
 
-                    self.emit8(0x03);
+                    self.emit_memory_operand(dst_reg, base, index, scale, disp);
+                }
+
+                // add dst, imm8 (byte width only)
+                Operand::Imm8(imm8) if width == Width::Byte => {
+                    self.emit_rex_prefix(width, dst_reg, 0);
 
-                    self.emit8(0x80
-                        | ((dst_reg & 0x7) << 3)
-                        | (src_reg & 0x7));
+                    self.emit8(0x80);
 
-                    self.emit32(src_offset);
+                    self.emit8(0xC0 | (dst_reg & 0x7));
+
+                    self.emit8(imm8);
                 }
 
                 // add dst, imm32
                 Operand::Imm32(imm32) => {
-                    self.emit_rex_prefix(dst_reg, 0);
+                    assert!(matches!(width, Width::Dword | Width::Qword), "a 32-bit immediate requires Dword or Qword width");
+
+                    self.emit_rex_prefix(width, dst_reg, 0);
 
                     self.emit8(0x81);
 
@@ -222,9 +470,10 @@ impl Assembler {
     /// This function generates a sub instruction that subtracts the source from the destination.
     ///
     /// Params:
+    ///  - `width`: the operand size
     ///  - `dst`: the destination operand
     ///  - `src`: the source operand
-    pub fn sub<A: Into<Operand>, B: Into<Operand>>(&mut self, dst: A, src: B) {
+    pub fn sub<A: Into<Operand>, B: Into<Operand>>(&mut self, width: Width, dst: A, src: B) {
         let dst = dst.into();
         let src = src.into();
 
@@ -232,31 +481,40 @@ impl Assembler {
             Operand::Register(dst_reg) => match src {
                 // sub dst, src
                 Operand::Register(src_reg) => {
-                    self.emit_rex_prefix(dst_reg, src_reg);
+                    self.emit_rex_prefix(width, dst_reg, src_reg);
 
-                    self.emit8(0x29);
+                    self.emit8(if width == Width::Byte { 0x28 } else { 0x29 });
 
                     self.emit8(0xC0
                         | ((src_reg & 0x7) << 3)
                         | (dst_reg & 0x7));
                 }
 
-                // sub dst, [src+offset]
-                Operand::MemoryAndOffset(src_reg, src_offset) => {
-                    self.emit_rex_prefix(src_reg, dst_reg);
+                // sub dst, [mem]
+                Operand::Memory { base, index, scale, disp } => {
+                    self.emit_rex_prefix_mem(width, dst_reg, base, index);
+
+                    self.emit8(if width == Width::Byte { 0x2A } else { 0x2B });
+
+                    self.emit_memory_operand(dst_reg, base, index, scale, disp);
+                }
 
-                    self.emit8(0x2B);
+                // sub dst, imm8 (byte width only)
+                Operand::Imm8(imm8) if width == Width::Byte => {
+                    self.emit_rex_prefix(width, dst_reg, 0);
 
-                    self.emit8(0x80
-                        | ((dst_reg & 0x7) << 3)
-                        | (src_reg & 0x7));
+                    self.emit8(0x80);
 
-                    self.emit32(src_offset);
+                    self.emit8(0xE8 | (dst_reg & 0x7));
+
+                    self.emit8(imm8);
                 }
 
                 // sub dst, imm32
                 Operand::Imm32(imm32) => {
-                    self.emit_rex_prefix(dst_reg, 0);
+                    assert!(matches!(width, Width::Dword | Width::Qword), "a 32-bit immediate requires Dword or Qword width");
+
+                    self.emit_rex_prefix(width, dst_reg, 0);
 
                     self.emit8(0x81);
 
@@ -272,6 +530,174 @@ impl Assembler {
         }
     }
 
+    /// This function generates a xor instruction that xors the destination with the source.
+    ///
+    /// Params:
+    ///  - `width`: the operand size
+    ///  - `dst`: the destination operand
+    ///  - `src`: the source operand
+    pub fn xor<A: Into<Operand>, B: Into<Operand>>(&mut self, width: Width, dst: A, src: B) {
+        let dst = dst.into();
+        let src = src.into();
+
+        match dst {
+            Operand::Register(dst_reg) => match src {
+                // xor dst, src
+                Operand::Register(src_reg) => {
+                    self.emit_rex_prefix(width, dst_reg, src_reg);
+
+                    self.emit8(if width == Width::Byte { 0x30 } else { 0x31 });
+
+                    self.emit8(0xC0
+                        | ((src_reg & 0x7) << 3)
+                        | (dst_reg & 0x7));
+                }
+
+                // xor dst, [mem]
+                Operand::Memory { base, index, scale, disp } => {
+                    self.emit_rex_prefix_mem(width, dst_reg, base, index);
+
+                    self.emit8(if width == Width::Byte { 0x32 } else { 0x33 });
+
+                    self.emit_memory_operand(dst_reg, base, index, scale, disp);
+                }
+
+                // xor dst, imm8 (byte width only)
+                Operand::Imm8(imm8) if width == Width::Byte => {
+                    self.emit_rex_prefix(width, dst_reg, 0);
+
+                    self.emit8(0x80);
+
+                    self.emit8(0xF0 | (dst_reg & 0x7));
+
+                    self.emit8(imm8);
+                }
+
+                // xor dst, imm32
+                Operand::Imm32(imm32) => {
+                    assert!(matches!(width, Width::Dword | Width::Qword), "a 32-bit immediate requires Dword or Qword width");
+
+                    self.emit_rex_prefix(width, dst_reg, 0);
+
+                    self.emit8(0x81);
+
+                    self.emit8(0xF0 | (dst_reg & 0x7));
+
+                    self.emit32(imm32);
+                }
+
+                op => panic!("Invalid source: {:?}", op)
+            },
+            Operand::Memory { base: dst_base, index: dst_index, scale: dst_scale, disp: dst_disp } => match src {
+                // xor [mem], src
+                Operand::Register(src_reg) => {
+                    self.emit_rex_prefix_mem(width, src_reg, dst_base, dst_index);
+
+                    self.emit8(if width == Width::Byte { 0x30 } else { 0x31 });
+
+                    self.emit_memory_operand(src_reg, dst_base, dst_index, dst_scale, dst_disp);
+                }
+
+                op => panic!("Invalid source: {:?}", op)
+            },
+
+            op => panic!("Invalid destination: {:?}", op)
+        }
+    }
+
+    /// This function generates a cmp instruction that compares the destination to the source.
+    ///
+    /// Params:
+    ///  - `width`: the operand size
+    ///  - `dst`: the destination operand
+    ///  - `src`: the source operand
+    pub fn cmp<A: Into<Operand>, B: Into<Operand>>(&mut self, width: Width, dst: A, src: B) {
+        let dst = dst.into();
+        let src = src.into();
+
+        match dst {
+            Operand::Register(dst_reg) => match src {
+                // cmp dst, src
+                Operand::Register(src_reg) => {
+                    self.emit_rex_prefix(width, dst_reg, src_reg);
+
+                    self.emit8(if width == Width::Byte { 0x38 } else { 0x39 });
+
+                    self.emit8(0xC0
+                        | ((src_reg & 0x7) << 3)
+                        | (dst_reg & 0x7));
+                }
+
+                // cmp dst, [mem]
+                Operand::Memory { base, index, scale, disp } => {
+                    self.emit_rex_prefix_mem(width, dst_reg, base, index);
+
+                    self.emit8(if width == Width::Byte { 0x3A } else { 0x3B });
+
+                    self.emit_memory_operand(dst_reg, base, index, scale, disp);
+                }
+
+                // cmp dst, imm8 (byte width only)
+                Operand::Imm8(imm8) if width == Width::Byte => {
+                    self.emit_rex_prefix(width, dst_reg, 0);
+
+                    self.emit8(0x80);
+
+                    self.emit8(0xF8 | (dst_reg & 0x7));
+
+                    self.emit8(imm8);
+                }
+
+                // cmp dst, imm32
+                Operand::Imm32(imm32) => {
+                    assert!(matches!(width, Width::Dword | Width::Qword), "a 32-bit immediate requires Dword or Qword width");
+
+                    self.emit_rex_prefix(width, dst_reg, 0);
+
+                    self.emit8(0x81);
+
+                    self.emit8(0xF8 | (dst_reg & 0x7));
+
+                    self.emit32(imm32);
+                }
+
+                op => panic!("Invalid source: {:?}", op)
+            },
+
+            op => panic!("Invalid destination: {:?}", op)
+        }
+    }
+
+    /// This function generates a test instruction that ANDs the destination with the source,
+    /// discarding the result but setting the flags.
+    ///
+    /// Params:
+    ///  - `dst`: the destination operand
+    ///  - `src`: the source operand
+    pub fn test<A: Into<Operand>, B: Into<Operand>>(&mut self, dst: A, src: B) {
+        let dst = dst.into();
+        let src = src.into();
+
+        match dst {
+            Operand::Register(dst_reg) => match src {
+                // test dst, src
+                Operand::Register(src_reg) => {
+                    self.emit_rex_prefix(Width::Qword, dst_reg, src_reg);
+
+                    self.emit8(0x85);
+
+                    self.emit8(0xC0
+                        | ((src_reg & 0x7) << 3)
+                        | (dst_reg & 0x7));
+                }
+
+                op => panic!("Invalid source: {:?}", op)
+            },
+
+            op => panic!("Invalid destination: {:?}", op)
+        }
+    }
+
     /// This function generates a near jump instruction and returns the offset to the jump destination.
     ///
     /// Params:
@@ -287,7 +713,7 @@ impl Assembler {
 
                 self.emit8(imm8);
 
-                return pos;
+                pos
             }
 
             Operand::Imm32(imm32) => {
@@ -297,13 +723,65 @@ impl Assembler {
 
                 self.emit32(imm32);
 
-                return pos;
+                pos
             }
 
             op => panic!("Invalid destination: {:?}", op)
         }
     }
 
+    /// This function generates a conditional near jump instruction and returns the offset to
+    /// the jump destination.
+    ///
+    /// Params:
+    ///  - `cond`: the condition that has to be met for the jump to be taken
+    ///  - `dst`: the destination operand
+    pub fn jcc<A: Into<Operand>>(&mut self, cond: Condition, dst: A) -> usize {
+        let dst = dst.into();
+
+        match dst {
+            Operand::Imm8(imm8) => {
+                self.emit8(0x70 | cond as u8);
+
+                let pos = self.code.len();
+
+                self.emit8(imm8);
+
+                pos
+            }
+
+            Operand::Imm32(imm32) => {
+                self.emit8(0x0F);
+                self.emit8(0x80 | cond as u8);
+
+                let pos = self.code.len();
+
+                self.emit32(imm32);
+
+                pos
+            }
+
+            op => panic!("Invalid destination: {:?}", op)
+        }
+    }
+
+    /// This function generates a conditional near (rel32) jump to `label`, resolving it
+    /// immediately if already bound or registering a fixup otherwise.
+    ///
+    /// Params:
+    ///  - `cond`: the condition that has to be met for the jump to be taken
+    ///  - `label`: the label to jump to
+    pub fn jcc_to(&mut self, cond: Condition, label: &mut Label) -> usize {
+        self.emit8(0x0F);
+        self.emit8(0x80 | cond as u8);
+
+        let pos = self.code.len();
+
+        self.resolve_label(label, FixupWidth::Rel32);
+
+        pos
+    }
+
     /// This function is used to emit a enter instruction.
     /// In this case we just do what enter does manually, that way
     /// we have more control over the alignment of the stack.
@@ -312,13 +790,13 @@ impl Assembler {
     /// - `stack_size`: the stack size, this will be aligned to 16 bytes
     pub fn enter(&mut self, stack_size: u32) {
         self.push(Registers::Rbp);
-        self.mov(Registers::Rbp, Registers::Rsp);
+        self.mov(Width::Qword, Registers::Rbp, Registers::Rsp);
 
         if stack_size > 0 {
             // align stack_size to 16 bytes
             let stack_size = (stack_size + 15) & !15;
 
-            self.sub(Registers::Rsp, stack_size);
+            self.sub(Width::Qword, Registers::Rsp, stack_size);
         }
     }
 
@@ -333,7 +811,7 @@ impl Assembler {
     ///  - `value`: the value to patch
     ///  - `offset`: the offset to patch
     pub fn patch32(&mut self, value: u32, offset: usize) {
-        self.code[offset + 0] = ((value >> 0) & 0xFF) as u8;
+        self.code[offset] = (value & 0xFF) as u8;
         self.code[offset + 1] = ((value >> 8) & 0xFF) as u8;
         self.code[offset + 2] = ((value >> 16) & 0xFF) as u8;
         self.code[offset + 3] = ((value >> 24) & 0xFF) as u8;
@@ -353,13 +831,14 @@ impl Assembler {
     pub fn push<S: Into<Operand>>(&mut self, src: S) {
         match src.into() {
             Operand::Register(reg) => {
-                self.emit_rex_prefix(reg, 0);
+                self.emit_rex_prefix(Width::Qword, reg, 0);
 
                 self.emit8(0x50 | (reg & 0x7));
             }
 
             Operand::Imm32(imm32) => {
-                // FIXME: this currently produces a push qword imm32 which is not what we want
+                // `push imm32` (0x68) always sign-extends to the stack width, which
+                // is 64 bits in long mode; there is no separate 32-bit push encoding.
                 self.emit8(0x68);
                 self.emit32(imm32);
             }
@@ -379,7 +858,7 @@ impl Assembler {
 
         match dst {
             Operand::Register(reg) => {
-                self.emit_rex_prefix(reg, 0);
+                self.emit_rex_prefix(Width::Qword, reg, 0);
 
                 self.emit8(0x58 | (reg & 0x7));
             }
@@ -399,7 +878,7 @@ impl Assembler {
     /// Params:
     /// - `dword`: the dword to add
     fn emit32(&mut self, dword: u32) {
-        self.code.push(((dword >> 0) & 0xFF) as u8);
+        self.code.push((dword & 0xFF) as u8);
         self.code.push(((dword >> 8) & 0xFF) as u8);
         self.code.push(((dword >> 16) & 0xFF) as u8);
         self.code.push(((dword >> 24) & 0xFF) as u8);
@@ -409,7 +888,7 @@ impl Assembler {
     /// Params:
     /// - `dword`: the dword to add
     fn emit64(&mut self, dword: u64) {
-        self.code.push(((dword >> 0) & 0xFF) as u8);
+        self.code.push((dword & 0xFF) as u8);
         self.code.push(((dword >> 8) & 0xFF) as u8);
         self.code.push(((dword >> 16) & 0xFF) as u8);
         self.code.push(((dword >> 24) & 0xFF) as u8);
@@ -419,38 +898,346 @@ impl Assembler {
         self.code.push(((dword >> 56) & 0xFF) as u8);
     }
 
-    /// This is a helper function that emits a REX prefix if necessary.
+    /// This is a helper function that emits the `0x66` operand-size prefix (for
+    /// 16-bit operands) and a REX prefix, if either is necessary for `width`.
     ///
     /// The REX prefix is of the form `0b0100WRXB`, where:
-    /// - `W` is the 64-bit operand size bit, this is always 1 in our case
+    /// - `W` selects a 64-bit operand size
     /// - `R` is the extension of the ModR/M `reg` field
-    /// - `X` is the extension of the SIB `index` field, this is always 0 in our case
+    /// - `X` is the extension of the SIB `index` field, this is always 0 here
     /// - `B` is the extension of the ModR/M `r/m` field or the SIB `base` field
     ///
+    /// For `Width::Dword` the REX prefix is omitted unless an extended (R8-R15)
+    /// register is used. For `Width::Byte` a bare REX (`0x40`) is still emitted
+    /// when SPL/BPL/SIL/DIL are accessed, since without it those encode AH/CH/DH/BH.
+    ///
+    /// Params:
+    /// - `width`: the operand size
+    /// - `reg1`: the ModR/M `r/m` field register (extends REX.B)
+    /// - `reg2`: the ModR/M `reg` field register (extends REX.R)
+    fn emit_rex_prefix(&mut self, width: Width, reg1: RegisterId, reg2: RegisterId) {
+        if width == Width::Word {
+            self.emit8(0x66);
+        }
+
+        let rex_w = decide!(width == Width::Qword, 1 << 3, 0);
+        let rex_r = decide!(reg2 >= 8, 1 << 2, 0);
+        let rex_b = decide!(reg1 >= 8, 1 << 0, 0);
+
+        let needs_bare_rex = width == Width::Byte && (is_uniform_byte_register(reg1) || is_uniform_byte_register(reg2));
+
+        if rex_w != 0 || rex_r != 0 || rex_b != 0 || needs_bare_rex {
+            self.emit8(0x40 | rex_w | rex_r | rex_b);
+        }
+    }
+
+    /// This is a helper function that emits the `0x66` operand-size prefix and a REX
+    /// prefix for an instruction whose r/m operand is an `Operand::Memory`, taking the
+    /// SIB `index` extension (REX.X) into account in addition to `reg` (REX.R) and
+    /// `base` (REX.B). See [`Assembler::emit_rex_prefix`] for the general rules.
+    ///
     /// Params:
-    /// - `src`: the source operand
-    /// - `dst`: the destination operand
-    fn emit_rex_prefix(&mut self, reg1: RegisterId, reg2: RegisterId) {
-        self.emit8(0x48
-            | decide!(reg2 >= 8, 1 << 2, 0)
-            | decide!(reg1 >= 8, 1 << 0, 0));
+    /// - `width`: the operand size
+    /// - `reg`: the ModR/M `reg` field register
+    /// - `base`: the memory operand's base register, if any
+    /// - `index`: the memory operand's index register, if any
+    fn emit_rex_prefix_mem(&mut self, width: Width, reg: RegisterId, base: Option<RegisterId>, index: Option<RegisterId>) {
+        if width == Width::Word {
+            self.emit8(0x66);
+        }
+
+        let rex_w = decide!(width == Width::Qword, 1 << 3, 0);
+        let rex_r = decide!(reg >= 8, 1 << 2, 0);
+        let rex_x = index.map_or(0, |index| decide!(index >= 8, 1 << 1, 0));
+        let rex_b = base.map_or(0, |base| decide!(base >= 8, 1 << 0, 0));
+
+        let needs_bare_rex = width == Width::Byte && is_uniform_byte_register(reg);
+
+        if rex_w != 0 || rex_r != 0 || rex_x != 0 || rex_b != 0 || needs_bare_rex {
+            self.emit8(0x40 | rex_w | rex_r | rex_x | rex_b);
+        }
+    }
+
+    /// This is a helper function that emits the ModR/M byte (and SIB byte and
+    /// displacement, if needed) for an instruction with a register `reg` field and
+    /// an `Operand::Memory` r/m operand.
+    ///
+    /// Params:
+    /// - `reg`: the ModR/M `reg` field register
+    /// - `base`: the memory operand's base register, or `None` for RIP-relative
+    /// - `index`: the memory operand's index register, if any
+    /// - `scale`: the scale applied to `index` (1, 2, 4, or 8)
+    /// - `disp`: the displacement
+    fn emit_memory_operand(&mut self, reg: RegisterId, base: Option<RegisterId>, index: Option<RegisterId>, scale: u8, disp: i32) {
+        let reg_field = (reg & 0x7) << 3;
+
+        let base_reg = match base {
+            // RIP-relative: mod=00, r/m=101, disp32
+            None => {
+                self.emit8(reg_field | 0b101);
+                self.emit32(disp as u32);
+
+                return;
+            }
+
+            Some(base_reg) => base_reg,
+        };
+
+        let base_low = base_reg & 0x7;
+
+        // RSP/R12 always require a SIB byte, even with no index.
+        let needs_sib = index.is_some() || base_low == 0b100;
+
+        // RBP/R13 as a base require an explicit (possibly zero) disp8,
+        // since mod=00 with r/m=101 means RIP-relative instead.
+        let needs_disp = base_low == 0b101;
+
+        let mode = if disp == 0 && !needs_disp {
+            0b00
+        } else if disp >= i8::MIN as i32 && disp <= i8::MAX as i32 {
+            0b01
+        } else {
+            0b10
+        };
+
+        if needs_sib {
+            self.emit8((mode << 6) | reg_field | 0b100);
+
+            let (index_low, scale_bits) = match index {
+                Some(index_reg) => (index_reg & 0x7, scale.trailing_zeros() as u8),
+                None => (0b100, 0),
+            };
+
+            self.emit8((scale_bits << 6) | (index_low << 3) | base_low);
+        } else {
+            self.emit8((mode << 6) | reg_field | base_low);
+        }
+
+        match mode {
+            0b00 => {}
+            0b01 => self.emit8(disp as i8 as u8),
+            0b10 => self.emit32(disp as u32),
+            _ => unreachable!(),
+        }
     }
 }
 
-impl Into<Operand> for Registers {
-    fn into(self) -> Operand {
-        Operand::Register(self as RegisterId)
+impl From<Registers> for Operand {
+    fn from(reg: Registers) -> Self {
+        Operand::Register(reg as RegisterId)
     }
 }
 
-impl Into<Operand> for u64 {
-    fn into(self) -> Operand {
-        Operand::Imm64(self)
+impl From<u64> for Operand {
+    fn from(imm64: u64) -> Self {
+        Operand::Imm64(imm64)
     }
 }
 
-impl Into<Operand> for u32 {
-    fn into(self) -> Operand {
-        Operand::Imm32(self)
+impl From<u32> for Operand {
+    fn from(imm32: u32) -> Self {
+        Operand::Imm32(imm32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaxpeax_arch::LengthedInstruction;
+    use yaxpeax_x86::amd64::InstDecoder;
+
+    /// This function assembles whatever `build` emits, decodes the resulting bytes
+    /// with yaxpeax-x86, and asserts that the decoded instruction's text matches
+    /// `expected` and that the decoder consumed exactly the bytes we emitted.
+    fn assert_encodes(build: fn(&mut Assembler), expected: &str) {
+        let mut asm = Assembler::new();
+        build(&mut asm);
+        let code = asm.finalize();
+
+        let decoder = InstDecoder::default();
+        let instruction = decoder
+            .decode_slice(&code)
+            .unwrap_or_else(|err| panic!("failed to decode {:02x?}: {}", code, err));
+
+        assert_eq!(instruction.len().to_const() as usize, code.len(), "decoded length did not match emitted length for {:02x?}", code);
+        assert_eq!(instruction.to_string(), expected, "decoded mnemonic/operands did not match for {:02x?}", code);
+    }
+
+    struct Case {
+        build: fn(&mut Assembler),
+        expected: &'static str,
+    }
+
+    const MOV_CASES: &[Case] = &[
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, Registers::Rcx), expected: "mov rax, rcx" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::R8, Registers::R9), expected: "mov r8, r9" },
+        Case { build: |asm| asm.mov(Width::Dword, Registers::Rax, Registers::Rcx), expected: "mov eax, ecx" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeef)), expected: "mov rax, qword [rcx + 0xbeef]" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::R9, Operand::memory_and_offset(Registers::R8, 0xbeef)), expected: "mov r9, qword [r8 + 0xbeef]" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, 0xdead_beefu64), expected: "mov rax, 0xdeadbeef" },
+    ];
+
+    const ADD_CASES: &[Case] = &[
+        Case { build: |asm| asm.add(Width::Qword, Registers::Rax, Registers::Rcx), expected: "add rax, rcx" },
+        Case { build: |asm| asm.add(Width::Qword, Registers::R8, Registers::R9), expected: "add r8, r9" },
+        Case { build: |asm| asm.add(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeef)), expected: "add rax, qword [rcx + 0xbeef]" },
+        Case { build: |asm| asm.add(Width::Qword, Registers::Rax, 0xbeefu32), expected: "add rax, 0xbeef" },
+        Case { build: |asm| asm.add(Width::Qword, Registers::R11, 0xbeefu32), expected: "add r11, 0xbeef" },
+    ];
+
+    const SUB_CASES: &[Case] = &[
+        Case { build: |asm| asm.sub(Width::Qword, Registers::Rax, Registers::Rcx), expected: "sub rax, rcx" },
+        Case { build: |asm| asm.sub(Width::Qword, Registers::R8, Registers::R9), expected: "sub r8, r9" },
+        Case { build: |asm| asm.sub(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeef)), expected: "sub rax, qword [rcx + 0xbeef]" },
+        Case { build: |asm| asm.sub(Width::Qword, Registers::Rax, 0xbeefu32), expected: "sub rax, 0xbeef" },
+    ];
+
+    const XOR_CASES: &[Case] = &[
+        Case { build: |asm| asm.xor(Width::Qword, Registers::Rax, Registers::Rcx), expected: "xor rax, rcx" },
+        Case { build: |asm| asm.xor(Width::Qword, Registers::R8, Registers::R9), expected: "xor r8, r9" },
+        Case { build: |asm| asm.xor(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeef)), expected: "xor rax, qword [rcx + 0xbeef]" },
+        Case { build: |asm| asm.xor(Width::Qword, Operand::memory_and_offset(Registers::Rcx, 0xbeef), Registers::Rax), expected: "xor qword [rcx + 0xbeef], rax" },
+        Case { build: |asm| asm.xor(Width::Qword, Registers::Rax, 0xbeefu32), expected: "xor rax, 0xbeef" },
+    ];
+
+    const CMP_CASES: &[Case] = &[
+        Case { build: |asm| asm.cmp(Width::Qword, Registers::Rax, Registers::Rcx), expected: "cmp rax, rcx" },
+        Case { build: |asm| asm.cmp(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeef)), expected: "cmp rax, qword [rcx + 0xbeef]" },
+        Case { build: |asm| asm.cmp(Width::Qword, Registers::Rax, 0xbeefu32), expected: "cmp rax, 0xbeef" },
+    ];
+
+    const MEMORY_OPERAND_CASES: &[Case] = &[
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, Operand::memory(Registers::Rcx, Registers::Rdx, 4, 0x10)), expected: "mov rax, qword [rcx + rdx * 4 + 0x10]" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, Operand::memory(Registers::R8, Registers::R9, 8, 0)), expected: "mov rax, qword [r8 + r9 * 8]" },
+        Case { build: |asm| asm.mov(Width::Qword, Registers::Rax, Operand::rip_relative(0x20)), expected: "mov rax, qword [rip + 0x20]" },
+    ];
+
+    #[test]
+    fn mov_round_trips_through_the_decoder() {
+        for case in MOV_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn add_round_trips_through_the_decoder() {
+        for case in ADD_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn sub_round_trips_through_the_decoder() {
+        for case in SUB_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn xor_round_trips_through_the_decoder() {
+        for case in XOR_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn push_imm32_round_trips_through_the_decoder() {
+        assert_encodes(|asm| asm.push(0xbeefu32), "push 0xbeef");
+    }
+
+    #[test]
+    fn cmp_round_trips_through_the_decoder() {
+        for case in CMP_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn memory_operand_round_trips_through_the_decoder() {
+        for case in MEMORY_OPERAND_CASES {
+            assert_encodes(case.build, case.expected);
+        }
+    }
+
+    #[test]
+    fn test_round_trips_through_the_decoder() {
+        assert_encodes(|asm| asm.test(Registers::Rax, Registers::Rcx), "test rax, rcx");
+    }
+
+    #[test]
+    fn jcc_imm8_round_trips_through_the_decoder() {
+        assert_encodes(|asm| { asm.jcc(Condition::Equal, Operand::Imm8(5)); }, "jz $+0x5");
+    }
+
+    #[test]
+    fn jcc_imm32_round_trips_through_the_decoder() {
+        assert_encodes(|asm| { asm.jcc(Condition::NotEqual, Operand::Imm32(0x100)); }, "jnz $+0x100");
+    }
+
+    #[test]
+    fn jcc_to_round_trips_through_the_decoder() {
+        let mut asm = Assembler::new();
+        let mut label = Label::new();
+
+        asm.jcc_to(Condition::Equal, &mut label);
+        asm.ret();
+        asm.bind(&mut label);
+
+        let code = asm.finalize();
+
+        let decoder = InstDecoder::default();
+        let instruction = decoder
+            .decode_slice(&code)
+            .unwrap_or_else(|err| panic!("failed to decode {:02x?}: {}", code, err));
+
+        assert_eq!(instruction.len().to_const() as usize, 6, "near jcc should be a 6-byte instruction");
+        assert!(instruction.to_string().starts_with("jz"), "expected a jz, got {}", instruction);
+    }
+
+    #[test]
+    #[should_panic(expected = "RSP/R12 cannot be used as an index register")]
+    fn memory_rejects_rsp_as_index() {
+        Operand::memory(Registers::Rax, Registers::Rsp, 1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "RSP/R12 cannot be used as an index register")]
+    fn memory_rejects_r12_as_index() {
+        Operand::memory(Registers::Rax, Registers::R12, 1, 0);
+    }
+
+    #[test]
+    fn jmp_short_round_trips_through_the_decoder() {
+        let mut asm = Assembler::new();
+        let mut label = Label::new();
+
+        asm.jmp_short(&mut label);
+        asm.ret();
+        asm.bind(&mut label);
+
+        let code = asm.finalize();
+
+        let decoder = InstDecoder::default();
+        let instruction = decoder
+            .decode_slice(&code)
+            .unwrap_or_else(|err| panic!("failed to decode {:02x?}: {}", code, err));
+
+        assert_eq!(instruction.len().to_const() as usize, 2, "short jump should be a 2-byte instruction");
+        assert!(instruction.to_string().starts_with("jmp"), "expected a jmp, got {}", instruction);
+    }
+
+    #[test]
+    #[should_panic(expected = "rel8 displacement out of range")]
+    fn jmp_short_panics_when_target_is_out_of_range() {
+        let mut asm = Assembler::new();
+        let mut label = Label::new();
+
+        asm.jmp_short(&mut label);
+
+        for _ in 0..200 {
+            asm.ret();
+        }
+
+        asm.bind(&mut label);
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
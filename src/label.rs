@@ -0,0 +1,45 @@
+/// The width of the relative displacement that still needs to be patched in
+/// at a [`Label`] fixup site.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum FixupWidth {
+    /// An 8-bit `rel8` displacement, as used by short jumps.
+    Rel8,
+    /// A 32-bit `rel32` displacement, as used by near jumps and calls.
+    Rel32,
+}
+
+/// A pending fixup site: the byte offset of the displacement field inside
+/// the generated code, together with how wide that displacement is.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Fixup {
+    pub offset: usize,
+    pub width: FixupWidth,
+}
+
+/// A `Label` marks a position in the code generated by an [`Assembler`] that
+/// may be jumped or called to before it has actually been emitted.
+///
+/// Jumps/calls that target an unbound label emit a zero placeholder and
+/// record a [`Fixup`] site; once the label is bound with
+/// [`Assembler::bind`], every pending site is patched with the real
+/// displacement.
+///
+/// [`Assembler`]: crate::assembler::Assembler
+/// [`Assembler::bind`]: crate::assembler::Assembler::bind
+#[derive(Debug, Default)]
+pub struct Label {
+    pub(crate) position: Option<usize>,
+    pub(crate) fixups: Vec<Fixup>,
+}
+
+impl Label {
+    /// This function creates a new, unbound label.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This function returns the position the label is bound to, if any.
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+}
@@ -0,0 +1,4 @@
+pub mod assembler;
+pub mod label;
+pub mod object;
+pub mod rt;
@@ -1,34 +1,34 @@
 use std::path::Path;
-use crate::assembler::{Operand, Registers};
 
-mod assembler;
-
-use crate::assembler::Assembler;
+use lyth_codegen::assembler::{Assembler, Operand, Registers, Width};
+use lyth_codegen::object::ObjectWriter;
 
 fn main() {
     let mut asm = Assembler::new();
 
+    let demo_offset = asm.code.len();
+
     // asm.enter(0);
     // asm.leave();
     // asm.ret();
 
-    // asm.mov(Registers::Rax, Registers::Rcx);
-    // asm.mov(Registers::Rax, Registers::R8);
-    // asm.mov(Registers::Rax, 0xbeefu64);
-    // asm.mov(Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0u32));
-    // asm.mov(Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
-    // asm.mov(Registers::Rax, Operand::memory_and_offset(Registers::R9, 0xbeefu32));
+    // asm.mov(Width::Qword, Registers::Rax, Registers::Rcx);
+    // asm.mov(Width::Qword, Registers::Rax, Registers::R8);
+    // asm.mov(Width::Qword, Registers::Rax, 0xbeefu64);
+    // asm.mov(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0u32));
+    // asm.mov(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
+    // asm.mov(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::R9, 0xbeefu32));
     //
-    // asm.mov(Registers::R8, Registers::Rcx);
-    // asm.mov(Registers::R8, Registers::R9);
-    // asm.mov(Registers::R8, 0xbeefu64);
-    // asm.mov(Registers::R8, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
-    // asm.mov(Registers::R8, Operand::memory_and_offset(Registers::R9, 0xbeefu32));
+    // asm.mov(Width::Qword, Registers::R8, Registers::Rcx);
+    // asm.mov(Width::Qword, Registers::R8, Registers::R9);
+    // asm.mov(Width::Qword, Registers::R8, 0xbeefu64);
+    // asm.mov(Width::Qword, Registers::R8, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
+    // asm.mov(Width::Qword, Registers::R8, Operand::memory_and_offset(Registers::R9, 0xbeefu32));
     //
-    // asm.mov(Operand::memory_and_offset(Registers::Rax, 0xbeefu32), Registers::Rcx);
-    // asm.mov(Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::Rcx);
-    // asm.mov(Operand::memory_and_offset(Registers::Rax, 0xbeefu32), Registers::R9);
-    // asm.mov(Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::R9);
+    // asm.mov(Width::Qword, Operand::memory_and_offset(Registers::Rax, 0xbeefu32), Registers::Rcx);
+    // asm.mov(Width::Qword, Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::Rcx);
+    // asm.mov(Width::Qword, Operand::memory_and_offset(Registers::Rax, 0xbeefu32), Registers::R9);
+    // asm.mov(Width::Qword, Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::R9);
 
     // asm.push(Registers::R8);
     // asm.push(Registers::Rcx);
@@ -37,39 +37,49 @@ fn main() {
     // asm.pop(Registers::Rax);
     // asm.pop(Registers::R9);
 
-    // asm.add(Registers::Rax, Registers::Rcx);
-    // asm.add(Registers::R8, Registers::Rcx);
+    // asm.add(Width::Qword, Registers::Rax, Registers::Rcx);
+    // asm.add(Width::Qword, Registers::R8, Registers::Rcx);
     //
-    // asm.add(Registers::Rax, Registers::R8);
-    // asm.add(Registers::R9, Registers::R8);
+    // asm.add(Width::Qword, Registers::Rax, Registers::R8);
+    // asm.add(Width::Qword, Registers::R9, Registers::R8);
     //
-    // asm.add(Registers::Rax, 0xbeefu32);
-    // asm.add(Registers::Rax, Operand::memory_and_offset(Registers::Rax, 0xbeefu32));
-    // asm.add(Registers::Rax, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
+    // asm.add(Width::Qword, Registers::Rax, 0xbeefu32);
+    // asm.add(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rax, 0xbeefu32));
+    // asm.add(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
     //
-    // asm.add(Registers::R9, 0xbeefu32);
-    // asm.add(Registers::R11, Operand::memory_and_offset(Registers::Rax, 0xbeefu32));
-    // asm.add(Registers::R11, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
+    // asm.add(Width::Qword, Registers::R9, 0xbeefu32);
+    // asm.add(Width::Qword, Registers::R11, Operand::memory_and_offset(Registers::Rax, 0xbeefu32));
+    // asm.add(Width::Qword, Registers::R11, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
+
+    asm.xor(Width::Qword, Registers::Rax, Registers::Rcx);
+    asm.xor(Width::Qword, Registers::Rax, Registers::R8);
+    asm.xor(Width::Qword, Registers::R8, Registers::Rax);
+    asm.xor(Width::Qword, Registers::R8, Registers::R9);
 
-    asm.xor(Registers::Rax, Registers::Rcx);
-    asm.xor(Registers::Rax, Registers::R8);
-    asm.xor(Registers::R8, Registers::Rax);
-    asm.xor(Registers::R8, Registers::R9);
+    asm.xor(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
+    asm.xor(Width::Qword, Registers::Rax, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
+    asm.xor(Width::Qword, Registers::R9, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
+    asm.xor(Width::Qword, Registers::R11, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
 
-    asm.xor(Registers::Rax, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
-    asm.xor(Registers::Rax, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
-    asm.xor(Registers::R9, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32));
-    asm.xor(Registers::R11, Operand::memory_and_offset(Registers::R8, 0xbeefu32));
+    asm.xor(Width::Qword, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32), Registers::Rax);
+    asm.xor(Width::Qword, Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::Rax);
+    asm.xor(Width::Qword, Operand::memory_and_offset(Registers::Rcx, 0xbeefu32), Registers::R9);
+    asm.xor(Width::Qword, Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::R11);
 
-    asm.xor(Operand::memory_and_offset(Registers::Rcx, 0xbeefu32), Registers::Rax);
-    asm.xor(Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::Rax);
-    asm.xor(Operand::memory_and_offset(Registers::Rcx, 0xbeefu32), Registers::R9);
-    asm.xor(Operand::memory_and_offset(Registers::R8, 0xbeefu32), Registers::R11);
+    asm.xor(Width::Qword, Registers::Rax, 0xbeefu32);
+    asm.xor(Width::Qword, Registers::R11, 0xbeefu32);
 
-    asm.xor(Registers::Rax, 0xbeefu32);
-    asm.xor(Registers::R11, 0xbeefu32);
+    asm.ret();
 
+    let relocations = asm.relocations.clone();
     let code = asm.finalize();
 
-    std::fs::write(Path::new("test.o"), code).unwrap();
+    let mut object = ObjectWriter::new(code.into_vec());
+    object.define_symbol("demo", demo_offset as u64);
+
+    for relocation in relocations {
+        object.add_relocation(relocation);
+    }
+
+    std::fs::write(Path::new("test.o"), object.write()).unwrap();
 }
\ No newline at end of file
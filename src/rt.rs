@@ -0,0 +1,121 @@
+use std::ptr;
+
+/// A `Runtime` maps a finalized code buffer into executable memory so it can
+/// be called in-process.
+///
+/// The mapping is first created read/write, the code is copied in, and then
+/// the mapping is flipped to read/execute with `mprotect` so the memory is
+/// never simultaneously writable and executable (W^X). `Runtime` owns the
+/// mapping and unmaps it again on drop.
+pub struct Runtime {
+    base: *mut libc::c_void,
+    len: usize,
+}
+
+impl Runtime {
+    /// This function maps `code` into page-aligned, executable memory and
+    /// returns a `Runtime` that owns the mapping.
+    ///
+    /// Params:
+    ///  - `code`: the finalized code to map, as produced by
+    ///    [`Assembler::finalize`](crate::assembler::Assembler::finalize)
+    pub fn new(code: &[u8]) -> Self {
+        let len = page_align(code.len().max(1));
+
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        assert_ne!(base, libc::MAP_FAILED, "mmap failed");
+
+        unsafe {
+            ptr::copy_nonoverlapping(code.as_ptr(), base as *mut u8, code.len());
+
+            let result = libc::mprotect(base, len, libc::PROT_READ | libc::PROT_EXEC);
+
+            assert_eq!(result, 0, "mprotect failed");
+        }
+
+        Self { base, len }
+    }
+
+    /// This function returns the base address the code was loaded at, e.g.
+    /// to resolve relocations or labels against the real load address.
+    pub fn base(&self) -> *const u8 {
+        self.base as *const u8
+    }
+
+    /// This function reinterprets the mapped code as a callable function
+    /// pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure `F` accurately describes the calling
+    /// convention and signature of the code that was mapped.
+    pub unsafe fn as_fn<F>(&self) -> F {
+        assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*const ()>());
+
+        let base = self.base;
+        std::mem::transmute_copy(&base)
+    }
+
+    /// This function calls the mapped code as a `extern "sysv64" fn(u64) -> u64`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure the mapped code actually implements this
+    /// signature and calling convention.
+    pub unsafe fn call1(&self, arg: u64) -> u64 {
+        let f = self.as_fn::<extern "sysv64" fn(u64) -> u64>();
+        f(arg)
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base, self.len);
+        }
+    }
+}
+
+/// This function rounds `len` up to the next multiple of the system page
+/// size.
+fn page_align(len: usize) -> usize {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+
+    (len + page_size - 1) & !(page_size - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{Assembler, Registers, Width};
+
+    /// This test maps `long add_one(long x) { return x + 1; }` and calls it
+    /// through `Runtime::call1`, proving the mapped code is actually
+    /// executable and callable with the sysv64 calling convention.
+    #[test]
+    fn call1_runs_mapped_code_and_returns_its_result() {
+        let mut asm = Assembler::new();
+
+        // sysv64: the first integer argument arrives in rdi.
+        asm.mov(Width::Qword, Registers::Rax, Registers::Rdi);
+        asm.add(Width::Qword, Registers::Rax, 1u32);
+        asm.ret();
+
+        let code = asm.finalize();
+        let runtime = Runtime::new(&code);
+
+        let result = unsafe { runtime.call1(41) };
+
+        assert_eq!(result, 42);
+    }
+}